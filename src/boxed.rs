@@ -1,7 +1,10 @@
+#[cfg(feature = "unsize")]
+use std::marker::Unsize;
 use std::{ops::{Deref, DerefMut}, fmt::Debug};
 
-use crate::TinyPtr;
+use crate::{TinyAllocError, TinyPtr};
 
+#[cfg(not(feature = "unsize"))]
 #[repr(transparent)]
 /// A tiny pointer to a heap allocated memory. As with all types of this crate, memory is
 /// allocated on the heap. It is equivalent to [`std::boxed::Box`].
@@ -13,6 +16,27 @@ use crate::TinyPtr;
 /// ```
 pub struct TinyBox<T>(TinyPtr<T>);
 
+#[cfg(feature = "unsize")]
+#[repr(transparent)]
+/// A tiny pointer to a heap allocated memory. As with all types of this crate, memory is
+/// allocated on the heap. It is equivalent to [`std::boxed::Box`].
+///
+/// ```rust
+/// use tinypointers::TinyBox;
+/// let x = TinyBox::new(42);
+/// println!("{}", *x); // prints 42
+/// ```
+///
+/// `TinyBox<T>` also supports unsized `T`, such as `TinyBox<[u8]>` or `TinyBox<dyn Trait>`.
+/// Unlike `Box<T>`, it does not coerce implicitly (there is no fat-pointer field for the
+/// compiler to rewrite) — go through [`TinyBox::unsize`] instead:
+/// ```rust
+/// use tinypointers::TinyBox;
+/// let x: TinyBox<[i32]> = TinyBox::new([1, 2, 3]).unsize();
+/// assert_eq!(&*x, &[1, 2, 3]);
+/// ```
+pub struct TinyBox<T: ?Sized>(TinyPtr<T>);
+
 macro_rules! impl_traits {
     ($derefable:ident) => {
         impl<T: std::fmt::Display> std::fmt::Display for $derefable<T> {
@@ -30,11 +54,22 @@ macro_rules! impl_traits {
     };
 }
 
+#[cfg(not(feature = "unsize"))]
 impl<T: Debug> std::fmt::Debug for TinyBox<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("TinyBox").field(self.deref()).finish()
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: Debug + ?Sized> std::fmt::Debug for TinyBox<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `T` may be `?Sized` here, so `self.deref()` (a `&T`) can't coerce straight to
+        // `&dyn Debug` the way it does in the sized impl above; go through the local binding so
+        // the reference-to-reference is an explicit, named step rather than a `&&` at the call site.
+        let value = self.deref();
+        f.debug_tuple("TinyBox").field(&value).finish()
+    }
+}
 
 impl<T: Clone> Clone for TinyBox<T> {
     fn clone(&self) -> Self {
@@ -58,26 +93,72 @@ impl<T> TinyBox<T> {
     pub fn new(value: T) -> Self {
         Self(TinyPtr::new(value))
     }
+
+    /// Like [`TinyBox::new`], but returns a [`TinyAllocError`] instead of panicking when the id
+    /// space is exhausted.
+    pub fn try_new(value: T) -> Result<Self, TinyAllocError> {
+        Ok(Self(TinyPtr::try_new(value)?))
+    }
+
+    /// Converts this box into an unsized `TinyBox<U>`, such as `TinyBox<[T]>` or
+    /// `TinyBox<dyn Trait>`, the same way `Box<T>` coerces but as an explicit step. Requires the
+    /// `unsize` feature.
+    /// ## Example
+    /// ```rust
+    /// use tinypointers::TinyBox;
+    /// let x: TinyBox<dyn std::fmt::Debug> = TinyBox::new(42).unsize();
+    /// ```
+    #[cfg(feature = "unsize")]
+    pub fn unsize<U: ?Sized>(self) -> TinyBox<U>
+    where
+        T: Unsize<U>,
+    {
+        let ptr = self.0;
+        std::mem::forget(self);
+        TinyBox(ptr.unsize())
+    }
 }
 
+#[cfg(not(feature = "unsize"))]
 impl<T> Deref for TinyBox<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.0.get() }
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> Deref for TinyBox<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.0.get() }
+    }
+}
 
+#[cfg(not(feature = "unsize"))]
 impl<T> DerefMut for TinyBox<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.0.get_mut() }
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> DerefMut for TinyBox<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.0.get_mut() }
+    }
+}
 
+#[cfg(not(feature = "unsize"))]
 impl<T> std::ops::Drop for TinyBox<T> {
     fn drop(&mut self) {
         self.0.take();
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> std::ops::Drop for TinyBox<T> {
+    fn drop(&mut self) {
+        unsafe { self.0.drop_in_place() };
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -113,4 +194,26 @@ mod tests {
             assert_dropped!(__ind);
         }
     }
+
+    #[test]
+    #[cfg(feature = "unsize")]
+    fn unsized_slice_test() {
+        let b: TinyBox<[i32; 4]> = TinyBox::new([1, 2, 3, 4]);
+        let mut b: TinyBox<[i32]> = b.unsize();
+        assert_eq!(&*b, &[1, 2, 3, 4]);
+        b[0] = 42;
+        assert_eq!(&*b, &[42, 2, 3, 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "unsize")]
+    fn unsized_trait_object_test() {
+        make_drop_indicator!(__ind, b, 42i32);
+        let b = TinyBox::new(b);
+        let b: TinyBox<dyn std::fmt::Debug> = b.unsize();
+        assert_eq!(format!("{:?}", b), "TinyBox(42)");
+
+        std::mem::drop(b);
+        assert_dropped!(__ind);
+    }
 }
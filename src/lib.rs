@@ -1,5 +1,10 @@
+// `ptr_metadata`/`unsize` are nightly-only, so DST support (`TinyBox::unsize`) is opt-in behind
+// the `unsize` cargo feature instead of forcing every downstream user onto nightly.
+#![cfg_attr(feature = "unsize", feature(ptr_metadata, unsize))]
 #![doc = include_str!("../README.md")]
 use std::{marker::PhantomData, ptr::NonNull};
+#[cfg(feature = "unsize")]
+use std::marker::Unsize;
 
 #[cfg(all(feature="1byteid", feature="2byteid"))]
 compile_error!("Cannot enable both 1byteid and 2byteid features");
@@ -12,11 +17,37 @@ type RawId = std::num::NonZeroU16;
 type RawId = std::num::NonZeroU8;
 
 mod boxed;
+mod rc;
 mod sync;
 
 pub use boxed::TinyBox;
+pub use rc::{TinyRc, TinyRcWeak};
 pub use sync::{TinyArc, TinyWeak};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned when the tiny id space is exhausted and no new pointer can be allocated.
+///
+/// Returned by the `try_new` family of constructors instead of panicking, so that servers and
+/// other long-running processes can degrade gracefully when the id table fills up.
+pub struct TinyAllocError;
+
+impl std::fmt::Display for TinyAllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("no more slots available, consider increasing the id size")
+    }
+}
+
+impl std::error::Error for TinyAllocError {}
+
+/// Returns how many more tiny pointers can be allocated before the id space is exhausted.
+///
+/// Useful to check capacity ahead of time, especially with the `1byteid` feature where only 255
+/// slots exist.
+pub fn remaining_slots() -> usize {
+    MEMORY.remaining_slots()
+}
+
+#[cfg(not(feature = "unsize"))]
 #[derive(Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 /// A tiny pointer to a mutable value of type `T`. As with all types of this crate, memory is allocated on the heap.
@@ -28,21 +59,75 @@ pub use sync::{TinyArc, TinyWeak};
 /// ```
 pub struct TinyPtr<T>(RawId, PhantomData<*mut T>);
 
+#[cfg(feature = "unsize")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+/// A tiny pointer to a mutable value of type `T`. As with all types of this crate, memory is allocated on the heap.
+///
+/// With the `unsize` feature enabled, `T` may also be unsized (`[U]`, `dyn Trait`, ...) via
+/// [`TinyBox::unsize`](crate::TinyBox::unsize).
+/// ```rust
+/// use tinypointers::TinyPtr;
+///
+/// let x = TinyPtr::new(42);
+/// println!("{}", unsafe { *x.get() }); // prints 42
+/// ```
+pub struct TinyPtr<T: ?Sized>(RawId, PhantomData<*mut T>);
+
+#[cfg(not(feature = "unsize"))]
 impl<T> Clone for TinyPtr<T> {
     fn clone(&self) -> Self {
         *self
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> Clone for TinyPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
 
+#[cfg(not(feature = "unsize"))]
 impl<T> Copy for TinyPtr<T> {}
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> Copy for TinyPtr<T> {}
 
 
 impl<T> TinyPtr<T> {
     pub fn new(value: T) -> Self {
         MEMORY.insert_value(Value::from(Box::from(value)))
     }
+
+    /// Like [`TinyPtr::new`], but returns a [`TinyAllocError`] instead of panicking when the id
+    /// space is exhausted.
+    pub fn try_new(value: T) -> Result<Self, TinyAllocError> {
+        MEMORY.try_insert_value(Value::from(Box::from(value)))
+    }
+
+    /// Takes ownership of the value and returns it.
+    ///
+    /// The underlying memory is freed.
+    pub fn take(self) -> T {
+        unsafe { MEMORY.take(self) }
+    }
+
+    /// Converts this pointer into an unsized `TinyPtr<U>`, such as `TinyPtr<[T]>` or
+    /// `TinyPtr<dyn Trait>`, recording the real pointer metadata (slice length / vtable pointer)
+    /// in the slot so it can be recovered later.
+    ///
+    /// There is no fat-pointer field on `TinyPtr` for [`CoerceUnsized`](std::ops::CoerceUnsized)
+    /// to rewrite, so the metadata has to be computed and stored at the moment of unsizing
+    /// instead. Requires the `unsize` feature.
+    #[cfg(feature = "unsize")]
+    pub(crate) fn unsize<U: ?Sized>(self) -> TinyPtr<U>
+    where
+        T: Unsize<U>,
+    {
+        MEMORY.unsize::<T, U>(self)
+    }
 }
 
+#[cfg(not(feature = "unsize"))]
 impl<T> TinyPtr<T> {
     pub fn as_ptr(&self) -> *const T {
         unsafe { MEMORY.access(self) }
@@ -56,11 +141,34 @@ impl<T> TinyPtr<T> {
     pub unsafe fn get_mut<'a, 'b>(&'b mut self) -> &'a mut T {
         &mut *MEMORY.access(self)
     }
-    /// Takes ownership of the value and returns it.
+
+    /// Returns the internal id of the pointer.
     ///
-    /// The underlying memory is freed.
-    pub fn take(self) -> T {
-        unsafe { MEMORY.take(self) }
+    /// This is used for debugging purposes.
+    pub fn id(&self) -> RawId {
+        self.0
+    }
+}
+
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> TinyPtr<T> {
+    pub fn as_ptr(&self) -> *const T {
+        unsafe { MEMORY.access(self) }
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        unsafe { MEMORY.access(self) }
+    }
+    pub unsafe fn get<'a, 'b>(&'b self) -> &'a T {
+        &*MEMORY.access(self)
+    }
+    pub unsafe fn get_mut<'a, 'b>(&'b mut self) -> &'a mut T {
+        &mut *MEMORY.access(self)
+    }
+
+    /// Drops the value in place and frees the underlying memory, without materializing it by
+    /// value. Unlike [`TinyPtr::take`], this works for unsized `T`.
+    unsafe fn drop_in_place(self) {
+        MEMORY.drop_in_place(self)
     }
 
     /// Returns the internal id of the pointer.
@@ -71,19 +179,33 @@ impl<T> TinyPtr<T> {
     }
 }
 
+#[cfg(not(feature = "unsize"))]
 impl<T> From<Box<T>> for TinyPtr<T> {
     fn from(value: Box<T>) -> Self {
         MEMORY.insert_value(Value::from(value))
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> From<Box<T>> for TinyPtr<T> {
+    fn from(value: Box<T>) -> Self {
+        MEMORY.insert_value(Value::from(value))
+    }
+}
 
 struct Value {
     val: NonNull<()>,
+    /// Type-erased `<T as Pointee>::Metadata` for the `T` this slot was created with, or for the
+    /// `U` it was last unsized to by `Memory::unsize`: `()` for sized types, a slice length, or a
+    /// vtable pointer. Only present with the `unsize`
+    /// feature, since reconstructing it requires the nightly `ptr_metadata` API.
+    #[cfg(feature = "unsize")]
+    metadata: usize,
 }
 
 unsafe impl Send for Value {}
 unsafe impl Sync for Value {}
 
+#[cfg(not(feature = "unsize"))]
 impl<T> From<Box<T>> for Value {
     fn from(value: Box<T>) -> Self {
         Self {
@@ -91,7 +213,18 @@ impl<T> From<Box<T>> for Value {
         }
     }
 }
+#[cfg(feature = "unsize")]
+impl<T: ?Sized> From<Box<T>> for Value {
+    fn from(value: Box<T>) -> Self {
+        let ptr = NonNull::from(Box::leak(value));
+        Self {
+            val: ptr.cast(),
+            metadata: erase_metadata(std::ptr::metadata(ptr.as_ptr())),
+        }
+    }
+}
 
+#[cfg(not(feature = "unsize"))]
 impl Value {
     unsafe fn get<T>(&self) -> *mut T {
         std::mem::transmute(self.val)
@@ -100,6 +233,46 @@ impl Value {
         Box::from_raw(self.val.as_ptr() as *mut T)
     }
 }
+#[cfg(feature = "unsize")]
+impl Value {
+    unsafe fn get<T: ?Sized>(&self) -> *mut T {
+        std::ptr::from_raw_parts_mut(self.val.as_ptr(), restore_metadata(self.metadata))
+    }
+    unsafe fn into_box<T: ?Sized>(self) -> Box<T> {
+        Box::from_raw(self.get::<T>())
+    }
+}
+
+/// Type-erases a `core::ptr::Pointee::Metadata` (a ZST for sized types, a `usize` length for
+/// slices, or a vtable pointer for trait objects) into a `usize` so `Value` can stay a plain thin
+/// pointer plus a word, regardless of what `T` it was created with.
+#[cfg(feature = "unsize")]
+fn erase_metadata<M: Copy>(metadata: M) -> usize {
+    assert!(std::mem::size_of::<M>() <= std::mem::size_of::<usize>());
+    let mut erased = 0usize;
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            &metadata as *const M as *const u8,
+            &mut erased as *mut usize as *mut u8,
+            std::mem::size_of::<M>(),
+        );
+    }
+    erased
+}
+
+/// Inverse of [`erase_metadata`].
+#[cfg(feature = "unsize")]
+fn restore_metadata<M: Copy>(erased: usize) -> M {
+    let mut metadata = std::mem::MaybeUninit::<M>::uninit();
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            &erased as *const usize as *const u8,
+            metadata.as_mut_ptr() as *mut u8,
+            std::mem::size_of::<M>(),
+        );
+        metadata.assume_init()
+    }
+}
 
 #[derive(Default)]
 struct Memory {
@@ -111,9 +284,38 @@ impl Memory {
     pub const fn new() -> Self {
         Self { available: Mutex::new(Vec::new()), map: RwLock::new(Vec::new()) }
     }
+    #[cfg(not(feature = "unsize"))]
     fn insert_value<T>(&self, value: Value) -> TinyPtr<T> {
-        if self.remaing_slots() == 0 {
-            panic!("No more slots available. Consider increasing the id size.")
+        self.try_insert_value(value)
+            .expect("No more slots available. Consider increasing the id size.")
+    }
+    #[cfg(feature = "unsize")]
+    fn insert_value<T: ?Sized>(&self, value: Value) -> TinyPtr<T> {
+        self.try_insert_value(value)
+            .expect("No more slots available. Consider increasing the id size.")
+    }
+    #[cfg(not(feature = "unsize"))]
+    fn try_insert_value<T>(&self, value: Value) -> Result<TinyPtr<T>, TinyAllocError> {
+        if self.remaining_slots() == 0 {
+            return Err(TinyAllocError);
+        }
+        let mut map = self.map.write();
+        let idx = match self.available.lock().pop() {
+            None => {
+                map.push(value.into());
+                RawId::new(map.len() as _).unwrap()
+            },
+            Some(idx) => {
+                map[idx.get() as usize - 1] = value.into();
+                idx
+            },
+        };
+        Ok(TinyPtr(idx, PhantomData))
+    }
+    #[cfg(feature = "unsize")]
+    fn try_insert_value<T: ?Sized>(&self, value: Value) -> Result<TinyPtr<T>, TinyAllocError> {
+        if self.remaining_slots() == 0 {
+            return Err(TinyAllocError);
         }
         let mut map = self.map.write();
         let idx = match self.available.lock().pop() {
@@ -126,21 +328,41 @@ impl Memory {
                 idx
             },
         };
-        TinyPtr(idx, PhantomData)
+        Ok(TinyPtr(idx, PhantomData))
     }
-    fn remaing_slots(&self) -> usize {
+    fn remaining_slots(&self) -> usize {
         self.available.lock().len() + (RawId::MAX.get() as usize - self.map.read().len())
-            
+
     }
+    #[cfg(not(feature = "unsize"))]
     unsafe fn access<T>(&self, idx: &TinyPtr<T>) -> *mut T {
         let map = self.map.read();
         map.get(idx.0.get() as usize - 1).expect("Index out of bounds").as_ref().expect("Pointer already freed").get()
     }
+    #[cfg(feature = "unsize")]
+    unsafe fn access<T: ?Sized>(&self, idx: &TinyPtr<T>) -> *mut T {
+        let map = self.map.read();
+        map.get(idx.0.get() as usize - 1).expect("Index out of bounds").as_ref().expect("Pointer already freed").get()
+    }
     unsafe fn take<T>(&self, idx: TinyPtr<T>) -> T {
         let mut map = self.map.write();
         let value = map.get_mut(idx.0.get() as usize - 1).expect("Index out of bounds").take().expect("Pointer already freed");
         *value.into_box()
     }
+    #[cfg(feature = "unsize")]
+    unsafe fn drop_in_place<T: ?Sized>(&self, idx: TinyPtr<T>) {
+        let mut map = self.map.write();
+        let value = map.get_mut(idx.0.get() as usize - 1).expect("Index out of bounds").take().expect("Pointer already freed");
+        drop(value.into_box::<T>());
+    }
+    #[cfg(feature = "unsize")]
+    fn unsize<T: Unsize<U>, U: ?Sized>(&self, idx: TinyPtr<T>) -> TinyPtr<U> {
+        let mut map = self.map.write();
+        let value = map.get_mut(idx.0.get() as usize - 1).expect("Index out of bounds").as_mut().expect("Pointer already freed");
+        let unsized_ptr: *mut U = unsafe { value.get::<T>() };
+        value.metadata = erase_metadata(std::ptr::metadata(unsized_ptr));
+        TinyPtr(idx.0, PhantomData)
+    }
 }
 
 static MEMORY: Memory = Memory::new();
@@ -257,4 +479,12 @@ pub(crate) mod tests {
         assert_eq!(std::mem::size_of::<Option<TinyPtr<u8>>>(), std::mem::size_of::<TinyPtr<u8>>());
     }
 
+    #[test]
+    fn try_new_succeeds_with_room_test() {
+        assert!(crate::remaining_slots() > 0);
+        let ptr = TinyPtr::try_new(42).unwrap();
+        assert_eq!(unsafe { *ptr.get() }, 42);
+        ptr.take();
+    }
+
 }
@@ -0,0 +1,264 @@
+use std::{cell::Cell, fmt::Debug, marker::PhantomData, mem::ManuallyDrop, ops::Deref};
+
+use crate::TinyPtr;
+
+struct RefCounted<T> {
+    strong: Cell<u32>,
+    weak: Cell<u32>,
+    value: ManuallyDrop<T>,
+}
+
+impl<T: Debug> Debug for RefCounted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RefCounted")
+            .field("strong", &self.strong.get())
+            .field("weak", &self.weak.get())
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+/// A weak reference to a [`TinyRc`], which is a single-threaded reference-counting tiny pointer.
+/// Essentially, it is non owning, and can be upgraded to a [`TinyRc`] at any time to access the
+/// data, as long as the data has not been dropped yet.
+/// ## Example
+/// ```rust
+/// use tinypointers::TinyRc;
+///
+/// let owned = TinyRc::new(42);
+/// let non_owned = TinyRc::downgrade(&owned);
+/// assert_eq!(*owned, 42);
+/// assert_eq!(*non_owned.upgrade().unwrap(), 42);
+/// ```
+pub struct TinyRcWeak<T>(TinyPtr<RefCounted<T>>, PhantomData<*const ()>);
+
+impl<T> Clone for TinyRcWeak<T> {
+    fn clone(&self) -> Self {
+        let refcounted = unsafe { &*self.0.get() };
+        refcounted.weak.set(refcounted.weak.get() + 1);
+        Self(self.0, PhantomData)
+    }
+}
+
+crate::boxed::impl_traits!(TinyRc);
+
+impl<T> TinyRcWeak<T> {
+    /// Attempts to upgrade the `TinyRcWeak` pointer to a `TinyRc`, extending the lifetime of the
+    /// data if successful.
+    ///
+    /// Returns `None` if the data has already been dropped, i.e. if there are no more `TinyRc`s
+    /// pointing to it.
+    pub fn upgrade(&self) -> Option<TinyRc<T>> {
+        let refcounted = unsafe { &*self.0.get() };
+        let strong = refcounted.strong.get();
+        if strong == 0 {
+            return None;
+        }
+        refcounted.strong.set(strong + 1);
+        Some(TinyRc(self.0, PhantomData))
+    }
+}
+
+impl<T> std::ops::Drop for TinyRcWeak<T> {
+    fn drop(&mut self) {
+        let refcounted = unsafe { &*self.0.get() };
+        let weak = refcounted.weak.get() - 1;
+        refcounted.weak.set(weak);
+        if weak == 0 {
+            // The last weak (and strong, since every strong holds the collective weak) reference
+            // is gone, so the slot can finally be reclaimed.
+            self.0.take();
+        }
+    }
+}
+
+/// A single-threaded reference-counting tiny pointer. As with all types of this crate, memory is
+/// allocated on the heap. It is equivalent to [`std::rc::Rc`]: the ref counts are plain `Cell`s
+/// instead of atomics, so clones and drops are cheaper than [`crate::TinyArc`], at the cost of
+/// `TinyRc` being neither `Send` nor `Sync`.
+///
+/// ```rust
+/// use tinypointers::TinyRc;
+///
+/// let x = TinyRc::new(42);
+/// let y = x.clone();
+/// println!("{}", *x); // prints 42
+/// println!("{}", *y); // prints 42
+/// // both x and y point to the same memory location
+/// ```
+pub struct TinyRc<T>(TinyPtr<RefCounted<T>>, PhantomData<*const ()>);
+
+impl<T> TinyRc<T> {
+    /// Allocates memory on the heap and then places `value` into it.
+    pub fn new(value: T) -> Self {
+        // The single strong reference also owns the collective weak reference, so weak starts at
+        // one rather than zero.
+        Self(
+            TinyPtr::new(RefCounted {
+                strong: Cell::new(1),
+                weak: Cell::new(1),
+                value: ManuallyDrop::new(value),
+            }),
+            PhantomData,
+        )
+    }
+    /// Constructs a new `TinyRc<T>` while giving you a `TinyRcWeak<T>` to the allocation, to
+    /// allow you to construct a `T` which holds a weak pointer to itself.
+    ///
+    /// See [`TinyArc::new_cyclic`](crate::TinyArc::new_cyclic) for the full semantics; this is the
+    /// single-threaded equivalent.
+    pub fn new_cyclic<F>(data_fn: F) -> Self where F: FnOnce(&TinyRcWeak<T>) -> T {
+        let mut ptr = TinyPtr::new(RefCounted {
+            strong: Cell::new(0),
+            weak: Cell::new(1),
+            value: unsafe { std::mem::MaybeUninit::<ManuallyDrop<T>>::uninit().assume_init() },
+        });
+        let weak = TinyRcWeak(ptr, PhantomData);
+        let data = data_fn(&weak);
+        unsafe {
+            let ptr = ptr.get_mut();
+            std::ptr::addr_of_mut!(ptr.value).write(ManuallyDrop::new(data));
+        }
+        let this = Self(ptr, PhantomData);
+        Self::increase_strong(&this);
+        // The freshly-minted strong reference now owns its own collective weak reference, on top
+        // of the one `weak` holds until it is dropped just below.
+        let refcounted = this.get();
+        refcounted.weak.set(refcounted.weak.get() + 1);
+        this
+    }
+    /// Checks whether the two `TinyRc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.0.id() == other.0.id()
+    }
+    /// Creates a [`TinyRcWeak`] pointer to this allocation.
+    ///
+    /// Weak references do not keep the inner value alive (only the slot that held it), and
+    /// cannot access the inner value except through [`TinyRcWeak::upgrade`].
+    pub fn downgrade(this: &Self) -> TinyRcWeak<T> {
+        let refcounted = this.get();
+        refcounted.weak.set(refcounted.weak.get() + 1);
+        TinyRcWeak(this.0, PhantomData)
+    }
+    /// Returns the number of strong (`TinyRc`) references to this allocation.
+    pub fn strong_count(this: &Self) -> u32 {
+        this.get().strong.get()
+    }
+
+    // internal apis
+
+    fn get(&self) -> &RefCounted<T> {
+        unsafe { &*self.0.get() }
+    }
+    fn increase_strong(this: &Self) -> u32 {
+        let refcounted = this.get();
+        let old = refcounted.strong.get();
+        refcounted.strong.set(old + 1);
+        old
+    }
+    fn decrease_strong(this: &Self) -> u32 {
+        let refcounted = this.get();
+        let old = refcounted.strong.get();
+        refcounted.strong.set(old - 1);
+        old
+    }
+}
+
+impl<T: Debug> Debug for TinyRc<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TinyRc")
+            .field("refcount", self.get())
+            .finish()
+    }
+}
+
+impl<T> Deref for TinyRc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        let refcounted = self.get();
+        if refcounted.strong.get() == 0 {
+            panic!("Attempted to dereference a TinyRc before it was built")
+        }
+        &refcounted.value
+    }
+}
+
+impl<T> Clone for TinyRc<T> {
+    fn clone(&self) -> Self {
+        Self::increase_strong(self);
+        Self(self.0, PhantomData)
+    }
+}
+
+impl<T> std::ops::Drop for TinyRc<T> {
+    fn drop(&mut self) {
+        if Self::decrease_strong(self) == 1 {
+            // We were the last strong reference: the value is dropped in place, but the slot
+            // itself stays alive until every TinyRcWeak (including the collective one we hold) is
+            // gone too.
+            unsafe { ManuallyDrop::drop(&mut self.0.get_mut().value) };
+            let refcounted = self.get();
+            let weak = refcounted.weak.get() - 1;
+            refcounted.weak.set(weak);
+            if weak == 0 {
+                self.0.take();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::{*, make_drop_indicator};
+
+    #[test]
+    fn single_rc_test() {
+        make_drop_indicator!(__ind, b, 42);
+        let b = TinyRc::new(b);
+        assert_eq!(*b, 42);
+        std::mem::drop(b);
+        assert_dropped!(__ind)
+    }
+
+    #[test]
+    fn multiple_refs_test() {
+        make_drop_indicator!(__ind, v, 30);
+        let i = TinyRc::new(v);
+        for _x in 0..200 {
+            let j = i.clone();
+            assert_eq!(*j, 30);
+        }
+        std::mem::drop(i);
+        assert_dropped!(__ind)
+    }
+
+    #[test]
+    fn weak_keeps_slot_alive_test() {
+        make_drop_indicator!(__ind, v, 7);
+        let strong = TinyRc::new(v);
+        let weak = TinyRc::downgrade(&strong);
+        std::mem::drop(strong);
+        assert_dropped!(__ind);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn make_cyclic_test() {
+        struct Narcissus {
+            _drop_indicator: DropIndicator<()>,
+            self_: TinyRcWeak<Narcissus>,
+        }
+
+        make_drop_indicator!(__ind, ind, ());
+        let narc = TinyRc::new_cyclic(|weak| {
+            Narcissus{self_: weak.clone(), _drop_indicator: ind}
+        });
+
+        assert!(TinyRc::ptr_eq(&narc, &narc.self_.upgrade().unwrap()));
+        std::mem::drop(narc);
+        assert_dropped!(__ind);
+    }
+}
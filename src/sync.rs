@@ -1,17 +1,18 @@
-use std::{fmt::Debug, ops::Deref, sync::atomic::AtomicU32};
+use std::{fmt::Debug, mem::ManuallyDrop, ops::Deref, sync::atomic::{AtomicU32, Ordering}};
 
-use crate::TinyPtr;
+use crate::{TinyAllocError, TinyPtr};
 
 #[derive(Debug)]
 struct RefCounted<T> {
-    count: AtomicU32,
-    value: T,
+    strong: AtomicU32,
+    weak: AtomicU32,
+    value: ManuallyDrop<T>,
 }
 
 #[derive(Debug)]
 /// A weak reference to a [`TinyArc`], which is a thread-safe reference-counting tiny pointer.
 /// Essentially, it is non owning, and can be upgraded to a [`TinyArc`] at any time to access the
-/// data.
+/// data, as long as the data has not been dropped yet.
 /// ## Example
 /// ```rust
 /// use tinypointers::TinyArc;
@@ -19,7 +20,7 @@ struct RefCounted<T> {
 /// let owned = TinyArc::new(42);
 /// let non_owned = TinyArc::downgrade(&owned);
 /// assert_eq!(*owned, 42);
-/// assert_eq!(*non_owned.upgrade(), 42);
+/// assert_eq!(*non_owned.upgrade().unwrap(), 42);
 /// ```
 pub struct TinyWeak<T>(TinyPtr<RefCounted<T>>);
 
@@ -28,6 +29,8 @@ unsafe impl<T: Send + Sync> Sync for TinyWeak<T> {}
 
 impl<T> Clone for TinyWeak<T> {
     fn clone(&self) -> Self {
+        let refcounted = unsafe { &*self.0.get() };
+        refcounted.weak.fetch_add(1, Ordering::Relaxed);
         Self(self.0)
     }
 }
@@ -35,7 +38,7 @@ impl<T> Clone for TinyWeak<T> {
 crate::boxed::impl_traits!(TinyArc);
 
 impl<T> TinyWeak<T> {
-    /// Attempts to upgrade the `TinyWeak` pointer to an `TinyArc`, extending the lifetime of the
+    /// Attempts to upgrade the `TinyWeak` pointer to a `TinyArc`, extending the lifetime of the
     /// data if successful.
     /// ## Example
     /// ```rust
@@ -46,15 +49,40 @@ impl<T> TinyWeak<T> {
     ///
     /// drop(owned);
     ///
-    /// let owned = non_owned.upgrade(); // Panics
+    /// assert!(non_owned.upgrade().is_none());
     /// ```
     ///
-    /// ## Panics
-    /// This panics if the data has since been dropped. I.E. if the `TinyArc` count is zero.
-    pub fn upgrade(&self) -> TinyArc<T> {
-        let arc = TinyArc(self.0);
-        TinyArc::increase_count(&arc);
-        arc
+    /// Returns `None` if the data has already been dropped, i.e. if there are no more `TinyArc`s
+    /// pointing to it.
+    pub fn upgrade(&self) -> Option<TinyArc<T>> {
+        let refcounted = unsafe { &*self.0.get() };
+        let mut strong = refcounted.strong.load(Ordering::Relaxed);
+        loop {
+            if strong == 0 {
+                return None;
+            }
+            match refcounted.strong.compare_exchange_weak(
+                strong,
+                strong + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(TinyArc(self.0)),
+                Err(current) => strong = current,
+            }
+        }
+    }
+}
+
+impl<T> std::ops::Drop for TinyWeak<T> {
+    fn drop(&mut self) {
+        let refcounted = unsafe { &*self.0.get() };
+        if refcounted.weak.fetch_sub(1, Ordering::Release) == 1 {
+            std::sync::atomic::fence(Ordering::Acquire);
+            // The last weak (and strong, since every strong holds the collective weak) reference
+            // is gone, so the slot can finally be reclaimed.
+            self.0.take();
+        }
     }
 }
 
@@ -84,41 +112,59 @@ impl<T> TinyArc<T> {
     /// let x = TinyArc::new(42);
     /// ```
     pub fn new(value: T) -> Self {
+        // The single strong reference also owns the collective weak reference, so weak starts at
+        // one rather than zero.
         Self(TinyPtr::new(RefCounted {
-            count: AtomicU32::new(1),
-            value,
+            strong: AtomicU32::new(1),
+            weak: AtomicU32::new(1),
+            value: ManuallyDrop::new(value),
         }))
     }
+    /// Like [`TinyArc::new`], but returns a [`TinyAllocError`] instead of panicking when the id
+    /// space is exhausted.
+    pub fn try_new(value: T) -> Result<Self, TinyAllocError> {
+        Ok(Self(TinyPtr::try_new(RefCounted {
+            strong: AtomicU32::new(1),
+            weak: AtomicU32::new(1),
+            value: ManuallyDrop::new(value),
+        })?))
+    }
     /// Constructs a new `TinyArc<T>` while giving you a `TinyWeak<T>` to the allocation, to allow
     /// you to construct a `T` which holds a weak pointer to itself.
     ///
     /// `new_cyclic` first allocates the managed allocation for the `TinyArc<T>`,
-    /// then calls your closure, giving it a `TinyWeak<T>` to this allocation,
+    /// then calls your closure, giving it a `&TinyWeak<T>` to this allocation,
     /// and only afterwards completes the construction of the `TinyArc<T>` by placing
-    /// the `T` returned from your closure into the allocation.
+    /// the `T` returned from your closure into the allocation. Clone the weak reference if `T`
+    /// needs to hold on to it.
     ///
     /// ## Panic
     /// Keep in mind that the `TinyArc<T>` is not fully constructed until `TinyArc<T>::new_cyclic`
-    /// returns. Calling [`TinyWeak::upgrade`] will cause a panic.
-    pub fn new_cyclic<F>(data_fn: F) -> Self where F: FnOnce(TinyWeak<T>) -> T {
+    /// returns. Calling [`TinyWeak::upgrade`] inside the closure does not panic; it returns `None`.
+    pub fn new_cyclic<F>(data_fn: F) -> Self where F: FnOnce(&TinyWeak<T>) -> T {
         let mut ptr = TinyPtr::new(RefCounted {
-            count: AtomicU32::new(0),
-            value: unsafe { std::mem::MaybeUninit::<T>::uninit().assume_init() },
+            strong: AtomicU32::new(0),
+            weak: AtomicU32::new(1),
+            value: unsafe { std::mem::MaybeUninit::<ManuallyDrop<T>>::uninit().assume_init() },
         });
-        let data = data_fn(TinyWeak(ptr));
+        let weak = TinyWeak(ptr);
+        let data = data_fn(&weak);
         unsafe {
             let ptr = ptr.get_mut();
-            std::ptr::addr_of_mut!(ptr.value).write(data);
+            std::ptr::addr_of_mut!(ptr.value).write(ManuallyDrop::new(data));
         }
         let this = Self(ptr);
-        Self::increase_count(&this);
+        Self::increase_strong(&this);
+        // The freshly-minted strong reference now owns its own collective weak reference, on top
+        // of the one `weak` holds until it is dropped just below.
+        this.get().weak.fetch_add(1, Ordering::Relaxed);
         this
     }
     /// Returns a raw pointer to the inner value.
     ///
     /// The pointer will be valid for as long as there are strong references to this allocation.
     pub fn as_ptr(this: &Self) -> *const T {
-        &this.get().value
+        &*this.get().value
     }
     /// Checks whether the two `TinyArc`s point to the same allocation.
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
@@ -128,23 +174,79 @@ impl<T> TinyArc<T> {
     ///
     /// Weak references do not keep the allocation alive, and cannot access the inner value.
     pub fn downgrade(this: &Self) -> TinyWeak<T> {
+        this.get().weak.fetch_add(1, Ordering::Relaxed);
         TinyWeak(this.0)
     }
+    /// Returns the number of strong (`TinyArc`) references to this allocation.
+    pub fn strong_count(this: &Self) -> u32 {
+        this.get().strong.load(Ordering::Relaxed)
+    }
+    /// Returns the number of weak (`TinyWeak`) references to this allocation, including the
+    /// collective weak reference held by the strong references.
+    pub fn weak_count(this: &Self) -> u32 {
+        this.get().weak.load(Ordering::Relaxed)
+    }
+    /// Returns the inner value, if `this` is the only strong reference to it.
+    ///
+    /// Otherwise, an `Err` is returned with the same `TinyArc` that was passed in.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this
+            .get()
+            .strong
+            .compare_exchange(1, 0, Ordering::Release, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(this);
+        }
+        std::sync::atomic::fence(Ordering::Acquire);
+        // Skip `TinyArc::drop`: it would try to decrease a strong count that is already zero.
+        let mut this = ManuallyDrop::new(this);
+        let value = unsafe { ManuallyDrop::take(&mut this.0.get_mut().value) };
+        if this.get().weak.fetch_sub(1, Ordering::Release) == 1 {
+            std::sync::atomic::fence(Ordering::Acquire);
+            this.0.take();
+        }
+        Ok(value)
+    }
+    /// Returns a mutable reference to the inner value, if there are no other `TinyArc` or
+    /// `TinyWeak` pointers to the same allocation.
+    ///
+    /// Returns `None` otherwise, since mutating the value would race with those other pointers.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        let refcounted = this.get();
+        if refcounted.strong.load(Ordering::Acquire) == 1 && refcounted.weak.load(Ordering::Acquire) == 1 {
+            Some(unsafe { &mut this.0.get_mut().value })
+        } else {
+            None
+        }
+    }
+    /// Returns a mutable reference into the given `TinyArc`, cloning the inner value into a
+    /// fresh allocation first if `this` isn't the unique owner.
+    ///
+    /// This is clone-on-write: as long as there are no other `TinyArc` or `TinyWeak` pointers to
+    /// this allocation, `this` is reused; otherwise `this` is repointed at a new allocation
+    /// holding a clone of the value.
+    pub fn make_mut(this: &mut Self) -> &mut T where T: Clone {
+        if Self::strong_count(this) != 1 || Self::weak_count(this) != 1 {
+            *this = Self::new((**this).clone());
+        }
+        unsafe { &mut this.0.get_mut().value }
+    }
 
     // internal apis
 
     fn get(&self) -> &RefCounted<T> {
         unsafe { &*self.0.get() }
     }
-    fn increase_count(this: &Self) -> u32 {
+    fn increase_strong(this: &Self) -> u32 {
         this.get()
-            .count
+            .strong
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
-    fn decrease_count(this: &Self) -> u32 {
+    fn decrease_strong(this: &Self) -> u32 {
         this.get()
-            .count
-            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed)
+            .strong
+            .fetch_sub(1, std::sync::atomic::Ordering::Release)
     }
 }
 
@@ -160,7 +262,7 @@ impl<T> Deref for TinyArc<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         let refcounted = self.get();
-        if dbg!(refcounted.count.load(std::sync::atomic::Ordering::Relaxed)) == 0 {
+        if refcounted.strong.load(std::sync::atomic::Ordering::Relaxed) == 0 {
             panic!("Attempted to dereference a TinyArc before it was built")
         }
         &refcounted.value
@@ -169,17 +271,23 @@ impl<T> Deref for TinyArc<T> {
 
 impl<T> Clone for TinyArc<T> {
     fn clone(&self) -> Self {
-        Self::increase_count(self);
+        Self::increase_strong(self);
         Self(self.0)
     }
 }
 
 impl<T> std::ops::Drop for TinyArc<T> {
     fn drop(&mut self) {
-        let owners = Self::decrease_count(self);
-        if owners == 1 {
-            // Drop the value if we're the last owner
-            self.0.take();
+        if Self::decrease_strong(self) == 1 {
+            std::sync::atomic::fence(Ordering::Acquire);
+            // We were the last strong reference: the value is dropped in place, but the slot
+            // itself stays alive until every TinyWeak (including the collective one we hold) is
+            // gone too.
+            unsafe { ManuallyDrop::drop(&mut self.0.get_mut().value) };
+            if self.get().weak.fetch_sub(1, Ordering::Release) == 1 {
+                std::sync::atomic::fence(Ordering::Acquire);
+                self.0.take();
+            }
         }
     }
 }
@@ -260,19 +368,78 @@ mod tests {
 
         make_drop_indicator!(__ind, ind, ());
         let narc = TinyArc::new_cyclic(|weak| {
-            Narcissus{self_: weak, _drop_indicator: ind}
+            Narcissus{self_: weak.clone(), _drop_indicator: ind}
         });
 
-        assert!(TinyArc::ptr_eq(&narc, &narc.self_.upgrade()));
+        assert!(TinyArc::ptr_eq(&narc, &narc.self_.upgrade().unwrap()));
         std::mem::drop(narc);
         assert_dropped!(__ind);
     }
 
     #[test]
-    #[should_panic]
-    fn make_cyclic_panic_test() {
+    fn make_cyclic_upgrade_before_built_test() {
         TinyArc::<()>::new_cyclic(|weak| {
-            weak.upgrade();
+            assert!(weak.upgrade().is_none());
         });
     }
+
+    #[test]
+    fn weak_keeps_slot_alive_test() {
+        make_drop_indicator!(__ind, v, 7);
+        let strong = TinyArc::new(v);
+        let weak = TinyArc::downgrade(&strong);
+        std::mem::drop(strong);
+        assert_dropped!(__ind);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn upgrade_returns_usable_arc_test() {
+        let strong = TinyArc::new(42);
+        let weak = TinyArc::downgrade(&strong);
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(*upgraded, 42);
+        assert_eq!(TinyArc::strong_count(&strong), 2);
+    }
+
+    #[test]
+    fn try_unwrap_unique_test() {
+        make_drop_indicator!(__ind, v, 42);
+        let arc = TinyArc::new(v);
+        let value = TinyArc::try_unwrap(arc).expect("should be unique");
+        assert_eq!(value, 42);
+        std::mem::drop(value);
+        assert_dropped!(__ind);
+    }
+
+    #[test]
+    fn try_unwrap_shared_test() {
+        let arc = TinyArc::new(42);
+        let clone = arc.clone();
+        let arc = TinyArc::try_unwrap(arc).unwrap_err();
+        assert_eq!(*arc, 42);
+        assert_eq!(*clone, 42);
+    }
+
+    #[test]
+    fn get_mut_test() {
+        let mut arc = TinyArc::new(42);
+        *TinyArc::get_mut(&mut arc).unwrap() = 7;
+        assert_eq!(*arc, 7);
+
+        let clone = arc.clone();
+        assert!(TinyArc::get_mut(&mut arc).is_none());
+        std::mem::drop(clone);
+    }
+
+    #[test]
+    fn make_mut_test() {
+        let mut arc = TinyArc::new(42);
+        let clone = arc.clone();
+
+        *TinyArc::make_mut(&mut arc) += 1;
+        assert_eq!(*arc, 43);
+        assert_eq!(*clone, 42);
+        assert!(!TinyArc::ptr_eq(&arc, &clone));
+    }
 }